@@ -1,91 +1,375 @@
 use std::collections::HashSet;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::ops::RangeInclusive;
 use std::path::Path;
 
-type Map = Vec<Vec<i32>>;
+use itertools::Itertools;
+
+/// Heights per cell; `None` marks an impassable `.` tile.
+type Map = Vec<Vec<Option<u8>>>;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
 
 fn parse_file_to_list(filepath: &str) -> Result<Map, io::Error> {
     let path = Path::new(filepath);
     let file = File::open(path)?;
-    let reader = io::BufReader::new(file);
+    parse_map(io::BufReader::new(file))
+}
 
-    let mut result: Vec<Vec<i32>> = Vec::new();
+fn parse_map(reader: impl BufRead) -> io::Result<Map> {
+    let mut result: Map = Vec::new();
 
-    for line in reader.lines() {
+    for (line_nr, line) in reader.lines().enumerate() {
         let line = line?; // Handle potential IO errors
-        let numbers: Vec<i32> = line
-            .chars() // Split by whitespace (space or tab)
-            .filter_map(|s| s.to_digit(10).map(|v| v as i32)) // Parse each part as an i32, ignore invalid entries
-            .collect();
-        result.push(numbers);
+        let cells = line
+            .trim_end()
+            .chars()
+            .enumerate()
+            .map(|(col, c)| match c {
+                '.' => Ok(None),
+                _ => c.to_digit(10).map(|v| Some(v as u8)).ok_or_else(|| {
+                    invalid_data(format!(
+                        "invalid character {:?} at line {}, column {}",
+                        c,
+                        line_nr + 1,
+                        col + 1
+                    ))
+                }),
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        if let Some(first) = result.first().filter(|first| first.len() != cells.len()) {
+            return Err(invalid_data(format!(
+                "line {} has width {}, expected {}",
+                line_nr + 1,
+                cells.len(),
+                first.len()
+            )));
+        }
+        result.push(cells);
     }
 
     Ok(result)
 }
 
 fn height_of(map: &Map, row: usize, col: usize) -> Option<i32> {
-    map.get(row).and_then(|r| r.get(col)).cloned()
+    map.get(row)?.get(col).copied().flatten().map(i32::from)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Neighbourhood {
+    Four,
+    Eight,
+}
+
+impl Neighbourhood {
+    fn offsets(self) -> &'static [(isize, isize)] {
+        match self {
+            Neighbourhood::Four => &[(1, 0), (0, 1), (-1, 0), (0, -1)],
+            Neighbourhood::Eight => &[
+                (1, 0),
+                (0, 1),
+                (-1, 0),
+                (0, -1),
+                (1, 1),
+                (1, -1),
+                (-1, 1),
+                (-1, -1),
+            ],
+        }
+    }
+}
+
+/// Which trails are valid: where they start and end, by how much the height may change
+/// per step and which cells count as adjacent. The default is the puzzle's rule set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrailRules {
+    start_height: i32,
+    end_height: i32,
+    step: RangeInclusive<i32>,
+    neighbourhood: Neighbourhood,
+}
+
+impl Default for TrailRules {
+    fn default() -> Self {
+        Self {
+            start_height: 0,
+            end_height: 9,
+            step: 1..=1,
+            neighbourhood: Neighbourhood::Four,
+        }
+    }
+}
+
+impl TrailRules {
+    fn allows_step(&self, from: i32, to: i32) -> bool {
+        self.step.contains(&(to - from))
+    }
+
+    /// Heights strictly increase (or decrease) along every trail, so trails cannot loop
+    /// and the number of distinct trails is finite.
+    fn is_monotonic(&self) -> bool {
+        *self.step.start() > 0 || *self.step.end() < 0
+    }
+}
+
+fn adjacent_cells(row: usize, col: usize, neighbourhood: Neighbourhood) -> Vec<(usize, usize)> {
+    neighbourhood
+        .offsets()
+        .iter()
+        .filter_map(|(d_row, d_col)| {
+            Some((
+                row.checked_add_signed(*d_row)?,
+                col.checked_add_signed(*d_col)?,
+            ))
+        })
+        .collect()
+}
+
+fn next_cells(map: &Map, rules: &TrailRules, row: usize, col: usize) -> Vec<(usize, usize)> {
+    let cur_height = height_of(map, row, col).unwrap();
+    adjacent_cells(row, col, rules.neighbourhood)
+        .into_iter()
+        .filter(|(r, c)| height_of(map, *r, *c).is_some_and(|h| rules.allows_step(cur_height, h)))
+        .collect()
+}
+
+fn trailheads(map: &Map, rules: &TrailRules) -> Vec<(usize, usize)> {
+    map.iter()
+        .enumerate()
+        .flat_map(|(row, line)| {
+            line.iter()
+                .enumerate()
+                .filter(|(_, height)| height.map(i32::from) == Some(rules.start_height))
+                .map(move |(col, _)| (row, col))
+        })
+        .collect()
 }
 
-fn score_of_trailhead(map: &Map, trailhead_row: usize, trailhead_col: usize) -> i32 {
+/// Part 1: number of distinct end cells reachable from the trailhead.
+fn score_of_trailhead(
+    map: &Map,
+    rules: &TrailRules,
+    trailhead_row: usize,
+    trailhead_col: usize,
+) -> usize {
     let mut next_options = vec![(trailhead_row, trailhead_col)];
+    let mut visited = HashSet::from([(trailhead_row, trailhead_col)]);
 
-    println!("Trailhead {} {}", trailhead_row, trailhead_col);
-    let mut found = Vec::<(usize, usize)>::new();
+    let mut found = HashSet::<(usize, usize)>::new();
     while let Some((row, col)) = next_options.pop() {
-        let cur_height = height_of(map, row, col).unwrap();
-        if cur_height == 9 {
-            found.push((row, col));
+        if height_of(map, row, col) == Some(rules.end_height) {
+            found.insert((row, col));
             continue;
         }
 
-        let adjacent_cells = vec![
-            (row + 1, col),
-            (row, col + 1),
-            (row.checked_add_signed(-1).unwrap_or(99999), col),
-            (row, col.checked_add_signed(-1).unwrap_or(99999)),
-        ];
-
-        for (adj_row, adj_col) in adjacent_cells {
-            if height_of(map, adj_row, adj_col)
-                .is_some_and(|adj_height| adj_height - cur_height == 1)
-            {
-                next_options.push((adj_row, adj_col));
+        for adj in next_cells(map, rules, row, col) {
+            if visited.insert(adj) {
+                next_options.push(adj);
             }
         }
     }
 
-    println!(
-        "Trailhead {} {} -> {}",
-        trailhead_row,
-        trailhead_col,
-        found.len()
-    );
-    found.len() as i32
+    found.len()
 }
 
-fn sum_of_trailhead_scores(map: &Map) -> i32 {
-    let mut result = 0_i32;
-    for row in 0..map.len() {
-        for col in 0..map[row].len() {
-            if map[row][col] == 0 {
-                result += score_of_trailhead(map, row, col);
-            }
-        }
+/// Number of distinct trails from every cell to an end cell.
+///
+/// Cells are processed in the reverse direction of the trails, so every cell only sums
+/// up the already known counts of the cells it can step to. Requires monotonic rules.
+fn trail_counts(map: &Map, rules: &TrailRules) -> Vec<Vec<u64>> {
+    let ascending = *rules.step.start() > 0;
+    let cells = map
+        .iter()
+        .enumerate()
+        .flat_map(|(row, line)| {
+            line.iter()
+                .enumerate()
+                .filter_map(move |(col, h)| Some((i32::from((*h)?), row, col)))
+        })
+        .sorted_by_key(|(h, _, _)| if ascending { -h } else { *h })
+        .collect_vec();
+
+    let mut counts: Vec<Vec<u64>> = map.iter().map(|row| vec![0; row.len()]).collect();
+    for (height, row, col) in cells {
+        counts[row][col] = if height == rules.end_height {
+            1
+        } else {
+            next_cells(map, rules, row, col)
+                .into_iter()
+                .map(|(r, c)| counts[r][c])
+                .sum()
+        };
+    }
+    counts
+}
+
+fn sum_of_trailhead_scores(map: &Map, rules: &TrailRules) -> usize {
+    trailheads(map, rules)
+        .into_iter()
+        .map(|(row, col)| score_of_trailhead(map, rules, row, col))
+        .sum()
+}
+
+/// Part 2: total number of distinct trails starting at any trailhead.
+fn sum_of_trailhead_ratings(map: &Map, rules: &TrailRules) -> u64 {
+    let counts = trail_counts(map, rules);
+    trailheads(map, rules)
+        .into_iter()
+        .map(|(row, col)| counts[row][col])
+        .sum()
+}
+
+/// Parses `--start N`, `--end N`, `--step MIN..MAX` (or a single delta) and
+/// `--neighbours 4|8` on top of the puzzle's default rules.
+fn parse_rules(args: &[String]) -> TrailRules {
+    let value_of = |flag: &str| {
+        args.iter().position(|a| a == flag).map(|i| {
+            args.get(i + 1)
+                .unwrap_or_else(|| panic!("missing value for {}", flag))
+        })
+    };
+    let parse_i32 = |v: &str| {
+        v.parse::<i32>()
+            .unwrap_or_else(|_| panic!("invalid number {:?}", v))
+    };
+
+    let mut rules = TrailRules::default();
+    if let Some(v) = value_of("--start") {
+        rules.start_height = parse_i32(v);
+    }
+    if let Some(v) = value_of("--end") {
+        rules.end_height = parse_i32(v);
+    }
+    if let Some(v) = value_of("--step") {
+        rules.step = match v.split_once("..") {
+            Some((min, max)) => parse_i32(min)..=parse_i32(max.trim_start_matches('=')),
+            None => parse_i32(v)..=parse_i32(v),
+        };
+    }
+    if let Some(v) = value_of("--neighbours") {
+        rules.neighbourhood = match v.as_str() {
+            "4" => Neighbourhood::Four,
+            "8" => Neighbourhood::Eight,
+            other => panic!("Usage: --neighbours <4|8>, got {:?}", other),
+        };
     }
-    result
+    rules
 }
 
 fn print_vec_without_separators(data: &Map) {
     data.iter().for_each(|row| {
-        row.iter().for_each(|&num| print!("{}", num));
+        row.iter().for_each(|cell| match cell {
+            Some(height) => print!("{}", height),
+            None => print!("."),
+        });
         println!();
     });
 }
 
 fn main() {
-    let map = parse_file_to_list(&std::env::args().nth(1).unwrap()).unwrap();
+    let args: Vec<String> = std::env::args().collect();
+    let map = parse_file_to_list(&args[1]).unwrap_or_else(|e| panic!("{}: {}", args[1], e));
+    let rules = parse_rules(&args[2..]);
+    assert!(
+        rules.is_monotonic(),
+        "--step must be strictly positive or strictly negative, got {:?}",
+        rules.step
+    );
+
     print_vec_without_separators(&map);
-    println!("{}", sum_of_trailhead_scores(&map));
+    println!("score {}", sum_of_trailhead_scores(&map, &rules));
+    println!("rating {}", sum_of_trailhead_ratings(&map, &rules));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+89010123
+78121874
+87430965
+96549874
+45678903
+32019012
+01329801
+10456732";
+
+    fn map_of(s: &str) -> Map {
+        parse_map(s.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn example_score_and_rating() {
+        let map = map_of(EXAMPLE);
+        let rules = TrailRules::default();
+        assert_eq!(sum_of_trailhead_scores(&map, &rules), 36);
+        assert_eq!(sum_of_trailhead_ratings(&map, &rules), 81);
+    }
+
+    #[test]
+    fn descending_rules_match_ascending() {
+        let map = map_of(EXAMPLE);
+        let args = ["--start", "9", "--end", "0", "--step", "-1"].map(String::from);
+        let rules = parse_rules(&args);
+        assert_eq!(
+            rules,
+            TrailRules {
+                start_height: 9,
+                end_height: 0,
+                step: -1..=-1,
+                ..TrailRules::default()
+            }
+        );
+        assert!(rules.is_monotonic());
+        assert_eq!(sum_of_trailhead_scores(&map, &rules), 36);
+        assert_eq!(sum_of_trailhead_ratings(&map, &rules), 81);
+    }
+
+    #[test]
+    fn eight_neighbours_allow_diagonal_steps() {
+        // Both trails from the 0 to the 2 need at least one diagonal step.
+        let map = map_of("055\n115\n525");
+        let four = TrailRules {
+            end_height: 2,
+            ..TrailRules::default()
+        };
+        let eight = TrailRules {
+            neighbourhood: Neighbourhood::Eight,
+            ..four.clone()
+        };
+        assert_eq!(sum_of_trailhead_scores(&map, &four), 0);
+        assert_eq!(sum_of_trailhead_ratings(&map, &four), 0);
+        assert_eq!(sum_of_trailhead_scores(&map, &eight), 1);
+        assert_eq!(sum_of_trailhead_ratings(&map, &eight), 2);
+    }
+
+    #[test]
+    fn impassable_tiles() {
+        let map = map_of("..90..9\n...1.98\n...2..7\n6543456\n765.987\n876....\n987....");
+        let rules = TrailRules::default();
+        assert_eq!(sum_of_trailhead_scores(&map, &rules), 4);
+        assert_eq!(sum_of_trailhead_ratings(&map, &rules), 13);
+    }
+
+    #[test]
+    fn ragged_rows_are_rejected() {
+        let error = parse_map("0123\n456\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "line 2 has width 3, expected 4");
+    }
+
+    #[test]
+    fn stray_character_reports_position() {
+        let error = parse_map("0123\n45x7\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "invalid character 'x' at line 2, column 3"
+        );
+    }
 }
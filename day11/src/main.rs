@@ -1,100 +1,417 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::fmt;
+use std::io;
 
-enum ResultOrSingleDigitNumber {
-    Result(usize),
-    SingleDigit { digit: usize, i: usize },
-}
-
-struct SingleDigit {
-    digit: usize,
-    i: usize,
-}
-
-// fn calculate_rule_lengths_per_blink() -> HashMap<usize, Vec<(usize, Vec<SingleDigit>)>> {
-//     let mut result = HashMap::<usize, Vec<(usize, Vec<SingleDigit>)>>::new();
-//
-//     for i in 0..10 {
-//         result.insert(i, vec![]);
-//     }
-//
-//     for i in 0..10 {
-//         println!("Calculating blinks of {}", i);
-//
-//         let result_vec = result.get_mut(&i).unwrap();
-//         let mut cur = vec![i];
-//         let mut tmp = vec![];
-//         for _ in 0..75 {
-//             tmp.clear();
-//             for v in &cur {
-//                 if *v == 0 {
-//                     tmp.push(*v);
-//                 } else {
-//                     let v_str = v.to_string();
-//                     if v_str.len() % 2 == 0 {
-//                         let (a, b) = v_str.split_at(v_str.len() / 2);
-//                         tmp.push(a.parse().unwrap());
-//                         tmp.push(b.parse().unwrap());
-//                     } else {
-//                         tmp.push(v * 2024);
-//                     }
-//                 }
-//             }
-//             std::mem::swap(&mut cur, &mut tmp);
-//
-//             for
-//         }
-//     }
-//
-//     result
-// }
-
-fn insert_or_add(v: usize, count: usize, m: &mut HashMap<usize, usize>) {
+use itertools::Itertools;
+
+/// Adds `count` stones with value `v`, or returns `None` if their count overflows.
+fn insert_or_add(v: u64, count: usize, m: &mut HashMap<u64, usize>) -> Option<()> {
     match m.entry(v) {
         std::collections::hash_map::Entry::Occupied(mut occupied_entry) => {
-            *occupied_entry.get_mut() += count;
+            let total = occupied_entry.get().checked_add(count)?;
+            *occupied_entry.get_mut() = total;
         }
         std::collections::hash_map::Entry::Vacant(vacant_entry) => {
             vacant_entry.insert(count);
         }
     }
+    Some(())
+}
+
+fn total_count(numbers_and_count: &HashMap<u64, usize>) -> Option<usize> {
+    numbers_and_count
+        .values()
+        .try_fold(0_usize, |acc, c| acc.checked_add(*c))
 }
 
-fn blink_75_times(init: &[usize]) -> usize {
-    let mut numbers_and_count = HashMap::<usize, usize>::new();
+#[derive(Debug)]
+enum StoneOverflow {
+    /// A stone's value does not fit into a `u64`.
+    Value { stone: u64, blink: usize },
+    /// The number of stones does not fit into a `usize`.
+    Count { blink: usize },
+}
+
+impl fmt::Display for StoneOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoneOverflow::Value { stone, blink } => {
+                write!(f, "stone {} overflows u64 in blink {}", stone, blink)
+            }
+            StoneOverflow::Count { blink } => {
+                write!(f, "number of stones overflows usize in blink {}", blink)
+            }
+        }
+    }
+}
+
+/// Splits a number with an even count of digits into its left and right half.
+fn split_digits(number: u64) -> Option<(u64, u64)> {
+    let digits = number.checked_ilog10()? + 1;
+    if digits % 2 != 0 {
+        return None;
+    }
+    let half = 10_u64.pow(digits / 2);
+    Some((number / half, number % half))
+}
+
+/// One way a stone can change during a blink. Rules are tried in order and the first
+/// matching rule decides what replaces the stone; stones no rule matches stay unchanged.
+trait StoneRule {
+    fn matches(&self, stone: u64) -> bool;
+
+    /// The stones replacing `stone`, or `None` if they do not fit into a `u64`.
+    fn apply(&self, stone: u64) -> Option<Vec<u64>>;
+}
+
+/// `replace FROM TO`: a stone engraved with `from` becomes `to`.
+struct Replace {
+    from: u64,
+    to: u64,
+}
+
+impl StoneRule for Replace {
+    fn matches(&self, stone: u64) -> bool {
+        stone == self.from
+    }
+
+    fn apply(&self, _: u64) -> Option<Vec<u64>> {
+        Some(vec![self.to])
+    }
+}
+
+/// `split-even-digits`: a stone with an even number of digits splits into its two halves.
+struct SplitEvenDigits;
+
+impl StoneRule for SplitEvenDigits {
+    fn matches(&self, stone: u64) -> bool {
+        split_digits(stone).is_some()
+    }
+
+    fn apply(&self, stone: u64) -> Option<Vec<u64>> {
+        split_digits(stone).map(|(a, b)| vec![a, b])
+    }
+}
+
+/// `multiply FACTOR`: every stone is multiplied by `factor`.
+struct Multiply {
+    factor: u64,
+}
+
+impl StoneRule for Multiply {
+    fn matches(&self, _: u64) -> bool {
+        true
+    }
+
+    fn apply(&self, stone: u64) -> Option<Vec<u64>> {
+        stone.checked_mul(self.factor).map(|v| vec![v])
+    }
+}
+
+type Rules = Vec<Box<dyn StoneRule>>;
+
+/// The rules from the puzzle.
+fn default_rules() -> Rules {
+    vec![
+        Box::new(Replace { from: 0, to: 1 }),
+        Box::new(SplitEvenDigits),
+        Box::new(Multiply { factor: 2024 }),
+    ]
+}
+
+/// Parses one rule per line (see `rules.txt`); empty lines and `#` comments are ignored.
+fn parse_rules(s: &str) -> io::Result<Rules> {
+    let mut rules: Rules = vec![];
+    for (line_nr, line) in s.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid rule {:?} at line {}", line, line_nr + 1),
+            )
+        };
+        let number = |v: &str| v.parse::<u64>().map_err(|_| invalid());
+
+        match line.split_whitespace().collect_vec().as_slice() {
+            [] => continue,
+            ["replace", from, to] => rules.push(Box::new(Replace {
+                from: number(from)?,
+                to: number(to)?,
+            })),
+            ["split-even-digits"] => rules.push(Box::new(SplitEvenDigits)),
+            ["multiply", factor] => rules.push(Box::new(Multiply {
+                factor: number(factor)?,
+            })),
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(rules)
+}
+
+/// Summary of the stones after one blink.
+#[derive(Debug)]
+struct BlinkStats {
+    blink: usize,
+    total: usize,
+    distinct: usize,
+    largest: u64,
+    /// The `k` most frequent values with their counts, most frequent first.
+    top: Vec<(u64, usize)>,
+}
+
+impl BlinkStats {
+    const CSV_HEADER: &'static str = "blink,total,distinct,largest,top";
+
+    fn of(blink: usize, numbers_and_count: &HashMap<u64, usize>, k: usize) -> Self {
+        Self {
+            blink,
+            total: numbers_and_count.values().sum(),
+            distinct: numbers_and_count.len(),
+            largest: numbers_and_count.keys().max().copied().unwrap_or(0),
+            top: numbers_and_count
+                .iter()
+                .map(|(v, c)| (*v, *c))
+                .sorted_by_key(|(v, c)| (Reverse(*c), *v))
+                .take(k)
+                .collect(),
+        }
+    }
+
+    fn top_to_string(&self, separator: &str) -> String {
+        self.top
+            .iter()
+            .map(|(v, c)| format!("{}={}", v, c))
+            .join(separator)
+    }
+
+    fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{}",
+            self.blink,
+            self.total,
+            self.distinct,
+            self.largest,
+            self.top_to_string(";")
+        )
+    }
+}
+
+impl fmt::Display for BlinkStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "blink {:>3}: {:>16} stones, {:>6} distinct, largest {:>20}, top {}",
+            self.blink,
+            self.total,
+            self.distinct,
+            self.largest,
+            self.top_to_string(" ")
+        )
+    }
+}
+
+/// Blinks until the last checkpoint and returns the stone count after each checkpoint.
+/// `checkpoints` must be sorted ascending. `on_blink` sees the stones before the first
+/// and after every blink.
+fn blink(
+    init: &[u64],
+    rules: &[Box<dyn StoneRule>],
+    checkpoints: &[usize],
+    on_blink: &mut impl FnMut(usize, &HashMap<u64, usize>),
+) -> Result<Vec<usize>, StoneOverflow> {
+    let mut numbers_and_count = HashMap::<u64, usize>::new();
 
     for v in init {
-        insert_or_add(*v, 1, &mut numbers_and_count);
+        insert_or_add(*v, 1, &mut numbers_and_count).ok_or(StoneOverflow::Count { blink: 0 })?;
+    }
+
+    on_blink(0, &numbers_and_count);
+    let mut counts = vec![];
+    let mut checkpoints = checkpoints.iter().peekable();
+    while checkpoints.next_if_eq(&&0).is_some() {
+        counts.push(init.len());
     }
 
-    let mut tmp = HashMap::<usize, usize>::new();
-    for _ in 0..75 {
+    let mut tmp = HashMap::<u64, usize>::new();
+    for blinks in 1.. {
+        if checkpoints.peek().is_none() {
+            break;
+        }
         tmp.clear();
         for (number, count) in &numbers_and_count {
-            if *number == 0 {
-                insert_or_add(1, *count, &mut tmp);
-            } else {
-                let k_str = number.to_string();
-                if k_str.len() % 2 == 0 {
-                    let (a, b) = k_str.split_at(k_str.len() / 2);
-                    insert_or_add(a.parse().unwrap(), *count, &mut tmp);
-                    insert_or_add(b.parse().unwrap(), *count, &mut tmp);
-                } else {
-                    insert_or_add(*number * 2024, *count, &mut tmp);
-                }
+            let next = match rules.iter().find(|rule| rule.matches(*number)) {
+                Some(rule) => rule.apply(*number).ok_or(StoneOverflow::Value {
+                    stone: *number,
+                    blink: blinks,
+                })?,
+                None => vec![*number],
+            };
+            for stone in next {
+                insert_or_add(stone, *count, &mut tmp)
+                    .ok_or(StoneOverflow::Count { blink: blinks })?;
             }
         }
         std::mem::swap(&mut numbers_and_count, &mut tmp);
-        println!(
-            "one iteration done. size {}, {:?}",
-            numbers_and_count.values().sum::<usize>(),
-            numbers_and_count
-        );
+        let total =
+            total_count(&numbers_and_count).ok_or(StoneOverflow::Count { blink: blinks })?;
+        on_blink(blinks, &numbers_and_count);
+        while checkpoints.next_if_eq(&&blinks).is_some() {
+            counts.push(total);
+        }
+    }
+    Ok(counts)
+}
+
+fn parse_stones(s: &str) -> io::Result<Vec<u64>> {
+    s.split_whitespace()
+        .map(|v| {
+            v.parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("invalid stone {:?}", v))
+            })
+        })
+        .collect()
+}
+
+fn main() -> io::Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let mut checkpoints: Vec<usize> = match args.iter().position(|a| a == "--blinks") {
+        Some(i) => {
+            let usage = "Usage: --blinks N[,M...]";
+            let value = args.get(i + 1).expect(usage).clone();
+            args.drain(i..=i + 1);
+            value.split(',').map(|n| n.parse().expect(usage)).collect()
+        }
+        None => vec![75],
+    };
+    checkpoints.sort_unstable();
+    checkpoints.dedup();
+
+    let rules = match args.iter().position(|a| a == "--rules") {
+        Some(i) => {
+            let path = args.get(i + 1).expect("Usage: --rules <file>").clone();
+            args.drain(i..=i + 1);
+            parse_rules(&std::fs::read_to_string(path)?)?
+        }
+        None => default_rules(),
+    };
+
+    let mut value_of = |flag: &str| {
+        let i = args.iter().position(|a| a == flag)?;
+        let value = args.get(i + 1).cloned();
+        args.drain(i..(i + 2).min(args.len()));
+        Some(value.unwrap_or_else(|| panic!("missing value for {}", flag)))
+    };
+    let csv_path = value_of("--csv");
+    let top_k = value_of("--top").map_or(3, |k| k.parse().expect("Usage: --top K"));
+    let print_stats = match args.iter().position(|a| a == "--stats") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+
+    // Either a path to a file with the stones or the stones themselves.
+    let stones = match args.as_slice() {
+        [path] if path.parse::<usize>().is_err() => parse_stones(&std::fs::read_to_string(path)?)?,
+        _ => parse_stones(&args.join(" "))?,
+    };
+    if stones.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Usage: day11 <input file | STONE...> [--blinks N[,M...]] [--rules file] \
+             [--stats] [--top K] [--csv path]",
+        ));
+    }
+
+    let mut stats = vec![];
+    let mut on_blink = |blink: usize, numbers_and_count: &HashMap<u64, usize>| {
+        if print_stats || csv_path.is_some() {
+            stats.push(BlinkStats::of(blink, numbers_and_count, top_k));
+        }
+    };
+    let counts = blink(&stones, &rules, &checkpoints, &mut on_blink)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    if print_stats {
+        stats.iter().for_each(|s| println!("{}", s));
+    }
+    if let Some(path) = csv_path {
+        let rows = stats.iter().map(BlinkStats::to_csv_row);
+        let csv = std::iter::once(BlinkStats::CSV_HEADER.to_string())
+            .chain(rows)
+            .join("\n");
+        std::fs::write(path, csv + "\n")?;
+    }
+    for (blinks, count) in checkpoints.iter().zip(counts) {
+        println!("{} blinks: {}", blinks, count);
     }
-    numbers_and_count.values().sum()
+    Ok(())
 }
 
-fn main() {
-    let input = [3028, 78, 973951, 5146801, 5, 0, 23533, 857];
-    // let example = vec![125, 17];
-    println!("{}", blink_75_times(&input));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_digits_near_u64_max() {
+        // u64::MAX has 20 digits.
+        assert_eq!(split_digits(u64::MAX), Some((1_844_674_407, 3_709_551_615)));
+        assert_eq!(split_digits(10_u64.pow(19)), Some((1_000_000_000, 0)));
+        assert_eq!(split_digits(10_u64.pow(19) - 1), None);
+        assert_eq!(split_digits(0), None);
+    }
+
+    #[test]
+    fn multiply_overflow_is_none() {
+        let rule = Multiply { factor: 2024 };
+        assert_eq!(
+            rule.apply(u64::MAX / 2024),
+            Some(vec![u64::MAX / 2024 * 2024])
+        );
+        assert_eq!(rule.apply(u64::MAX / 2024 + 1), None);
+    }
+
+    #[test]
+    fn stone_count_overflow_is_reported() {
+        // 0 -> 1 -> 2024 -> 20 24 -> 2 0 2 4 -> ... grows forever, so the count
+        // of stones eventually no longer fits into a usize.
+        let result = blink(&[0], &default_rules(), &[1000], &mut |_, _| {});
+        assert!(matches!(result, Err(StoneOverflow::Count { .. })));
+    }
+
+    #[test]
+    fn custom_rules() {
+        let rules = parse_rules(
+            "# zeros become sevens\nreplace 0 7\n\nsplit-even-digits\nmultiply 3 # triple\n",
+        )
+        .unwrap();
+        let mut last = HashMap::new();
+        let counts = blink(&[0, 5], &rules, &[1, 2, 3], &mut |_, numbers_and_count| {
+            last = numbers_and_count.clone()
+        })
+        .unwrap();
+
+        // 0 5 -> 7 15 -> 21 1 5 -> 2 1 3 15
+        assert_eq!(counts, vec![2, 3, 4]);
+        assert_eq!(last, HashMap::from([(2, 1), (1, 1), (3, 1), (15, 1)]));
+    }
+
+    #[test]
+    fn malformed_rule_reports_line() {
+        let error = parse_rules("replace 0 1\n\nmultiply\n").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 3"), "{}", error);
+
+        let error = parse_rules("multiply x").err().unwrap();
+        assert!(error.to_string().contains("line 1"), "{}", error);
+    }
+
+    #[test]
+    fn puzzle_example() {
+        let counts = blink(&[125, 17], &default_rules(), &[6, 25], &mut |_, _| {}).unwrap();
+        assert_eq!(counts, vec![22, 55312]);
+    }
 }
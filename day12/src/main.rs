@@ -1,9 +1,8 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, IsTerminal};
 use std::path::Path;
-
-use itertools::Itertools;
+use std::time::Instant;
 
 type Map = Vec<Vec<char>>;
 
@@ -23,7 +22,11 @@ fn read_file_to_map(file_path: &str) -> io::Result<Map> {
     Ok(map)
 }
 
-type Pos = (usize, usize); // row, col
+type Pos = (i32, i32); // row, col
+/// A region id and the cells belonging to it.
+type Component = (i32, Vec<Pos>);
+/// The region id of every cell; ids start at 1.
+type ComponentMap = Vec<Vec<i32>>;
 
 fn map_at(map: &Map, r: i32, c: i32) -> Option<char> {
     let row = usize::try_from(r).ok()?;
@@ -31,7 +34,47 @@ fn map_at(map: &Map, r: i32, c: i32) -> Option<char> {
     map.get(row).and_then(|v| v.get(col)).cloned()
 }
 
-fn find_connected_components(map: &mut Map) -> (Vec<(i32, Vec<(i32, i32)>)>, Vec<Vec<i32>>) {
+/// Labels all regions with a single flood fill per region. A cell is visited at most once,
+/// with `map_with_components` (0 = not labelled yet) doubling as the visited set.
+fn find_connected_components(map: &Map) -> (Vec<Component>, ComponentMap) {
+    let mut connected_components = vec![];
+    let mut map_with_components = vec![vec![0; map[0].len()]; map.len()];
+    let mut component_counter = 0;
+
+    for row in 0..map.len() {
+        for col in 0..map[row].len() {
+            if map_with_components[row][col] != 0 {
+                continue;
+            }
+
+            component_counter += 1;
+            let current = map[row][col];
+            let start = (row as i32, col as i32);
+            map_with_components[row][col] = component_counter;
+
+            let mut connected = vec![];
+            let mut to_search = vec![start];
+            while let Some((r, c)) = to_search.pop() {
+                connected.push((r, c));
+                for (r_next, c_next) in [(r - 1, c), (r + 1, c), (r, c - 1), (r, c + 1)] {
+                    if map_at(map, r_next, c_next) == Some(current)
+                        && map_with_components[r_next as usize][c_next as usize] == 0
+                    {
+                        map_with_components[r_next as usize][c_next as usize] = component_counter;
+                        to_search.push((r_next, c_next));
+                    }
+                }
+            }
+            connected_components.push((component_counter, connected))
+        }
+    }
+
+    (connected_components, map_with_components)
+}
+
+/// The original labelling, which searches all earlier regions for every cell. `--bench`
+/// times it against the flood fill and checks that both agree.
+fn find_connected_components_naive(map: &Map) -> (Vec<Component>, ComponentMap) {
     let mut connected_components = vec![];
     let mut map_with_components = vec![vec![0; map[0].len()]; map.len()];
     let mut component_counter = 0;
@@ -45,7 +88,7 @@ fn find_connected_components(map: &mut Map) -> (Vec<(i32, Vec<(i32, i32)>)>, Vec
             if connected_components
                 .iter()
                 .flat_map(|(_, v)| v)
-                .any(|e: &(i32, i32)| *e == (r, c))
+                .any(|e: &Pos| *e == (r, c))
             {
                 continue;
             }
@@ -56,7 +99,7 @@ fn find_connected_components(map: &mut Map) -> (Vec<(i32, Vec<(i32, i32)>)>, Vec
 
             let mut connnected = vec![];
             let mut to_search = vec![(r, c)];
-            let mut visited = HashSet::<(i32, i32)>::new();
+            let mut visited = HashSet::<Pos>::new();
             while let Some((r_next, c_next)) = to_search.pop() {
                 // println!("checking {},{}", r_next, c_next);
                 if visited.contains(&(r_next, c_next)) {
@@ -87,96 +130,396 @@ fn find_connected_components(map: &mut Map) -> (Vec<(i32, Vec<(i32, i32)>)>, Vec
     (connected_components, map_with_components)
 }
 
-// Function to print the 2D grid
-fn print_grid(grid: &Vec<Vec<i32>>) {
-    for row in grid {
-        for id in row {
-            print!("{}", id); // Print the formatted string "cn"
+/// Background colours from the 256-colour palette.
+const REGION_COLOURS: [u8; 12] = [196, 208, 226, 46, 51, 33, 201, 130, 34, 99, 248, 180];
+
+fn region_id_at(map: &ComponentMap, r: i32, c: i32) -> Option<i32> {
+    let row = usize::try_from(r).ok()?;
+    let col = usize::try_from(c).ok()?;
+    map.get(row).and_then(|v| v.get(col)).copied()
+}
+
+/// Picks a background colour per region id (index 0 is unused), greedily avoiding the
+/// colours of already coloured neighbours. Only if a region borders regions of every colour
+/// in `REGION_COLOURS` does it share one with a neighbour; `--fences` still tells them apart.
+fn region_colours(components: &ComponentMap, region_count: usize) -> Vec<u8> {
+    let mut neighbours = vec![HashSet::new(); region_count + 1];
+    for (row, line) in components.iter().enumerate() {
+        for (col, &id) in line.iter().enumerate() {
+            let right = line.get(col + 1);
+            let below = components.get(row + 1).map(|next| &next[col]);
+            for &other in right.into_iter().chain(below).filter(|&&other| other != id) {
+                neighbours[id as usize].insert(other as usize);
+                neighbours[other as usize].insert(id as usize);
+            }
+        }
+    }
+
+    let mut colours: Vec<Option<u8>> = vec![None; region_count + 1];
+    for id in 1..=region_count {
+        let taken: HashSet<u8> = neighbours[id].iter().filter_map(|&n| colours[n]).collect();
+        colours[id] = Some(
+            REGION_COLOURS
+                .into_iter()
+                .find(|c| !taken.contains(c))
+                .unwrap_or(REGION_COLOURS[id % REGION_COLOURS.len()]),
+        );
+    }
+    colours.into_iter().map(|c| c.unwrap_or(0)).collect()
+}
+
+/// The plant at the given cell, on its region's background colour if `colours` is given.
+fn coloured_plant(
+    map: &Map,
+    components: &ComponentMap,
+    row: usize,
+    col: usize,
+    colours: Option<&[u8]>,
+) -> String {
+    let plant = map[row][col];
+    match colours {
+        Some(colours) => {
+            let background = colours[components[row][col] as usize];
+            format!("\x1b[38;5;16;48;5;{}m{}\x1b[0m", background, plant)
+        }
+        None => plant.to_string(),
+    }
+}
+
+/// Renders the garden with one character per plot.
+fn render_regions(map: &Map, components: &ComponentMap, colours: Option<&[u8]>) -> String {
+    (0..map.len())
+        .map(|row| {
+            (0..map[row].len())
+                .map(|col| coloured_plant(map, components, row, col, colours))
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Renders the garden with box-drawing fences between regions. Plots sit on odd rows and
+/// columns of the output, fences and their junctions on the even ones in between.
+fn render_fences(map: &Map, components: &ComponentMap, colours: Option<&[u8]>) -> String {
+    let id = |r: i32, c: i32| region_id_at(components, r, c);
+    // Fence between two vertically / horizontally adjacent plots.
+    let horizontal_fence = |r: i32, c: i32| id(r - 1, c) != id(r, c);
+    let vertical_fence = |r: i32, c: i32| id(r, c - 1) != id(r, c);
+
+    let rows = map.len() as i32;
+    let cols = map[0].len() as i32;
+    let mut lines = vec![];
+    for r in 0..=rows {
+        // Fence line above row r.
+        let mut line = String::new();
+        for c in 0..=cols {
+            let up = vertical_fence(r - 1, c);
+            let down = vertical_fence(r, c);
+            let left = horizontal_fence(r, c - 1);
+            let right = horizontal_fence(r, c);
+            line.push(match (up, down, left, right) {
+                (true, true, true, true) => '┼',
+                (true, true, true, false) => '┤',
+                (true, true, false, true) => '├',
+                (true, true, false, false) => '│',
+                (true, false, true, true) => '┴',
+                (true, false, true, false) => '┘',
+                (true, false, false, true) => '└',
+                (false, true, true, true) => '┬',
+                (false, true, true, false) => '┐',
+                (false, true, false, true) => '┌',
+                (false, false, true, true) => '─',
+                (true, false, false, false) => '╵',
+                (false, true, false, false) => '╷',
+                (false, false, true, false) => '╴',
+                (false, false, false, true) => '╶',
+                (false, false, false, false) => ' ',
+            });
+            if c < cols {
+                line.push(if horizontal_fence(r, c) { '─' } else { ' ' });
+            }
+        }
+        lines.push(line);
+        if r == rows {
+            break;
+        }
+
+        // Row r itself, with the fences between its plots.
+        let mut line = String::new();
+        for c in 0..=cols {
+            line.push(if vertical_fence(r, c) { '│' } else { ' ' });
+            if c < cols {
+                line += &coloured_plant(map, components, r as usize, c as usize, colours);
+            }
         }
-        println!(); // Move to the next line after each row
+        lines.push(line);
     }
+    lines.join("\n")
 }
 
-fn has_id_at(id: i32, map: &Vec<Vec<i32>>, r: i32, c: i32) -> Option<bool> {
+fn has_id_at(id: i32, map: &ComponentMap, r: i32, c: i32) -> Option<bool> {
     let row = usize::try_from(r).ok()?;
     let col = usize::try_from(c).ok()?;
     map.get(row).and_then(|v| v.get(col)).map(|v| *v == id)
 }
 
-fn cost_of((comp_id, comp): &(i32, Vec<(i32, i32)>), map: &Vec<Vec<i32>>) -> usize {
-    println!("cost of {comp_id}");
-    let count_corners: i32 = comp
-        .iter()
-        .map(|(row, col)| {
-            let top = has_id_at(*comp_id, map, row - 1, *col).unwrap_or(false);
-            let bot = has_id_at(*comp_id, map, row + 1, *col).unwrap_or(false);
-            let left = has_id_at(*comp_id, map, *row, *col - 1).unwrap_or(false);
-            let right = has_id_at(*comp_id, map, *row, *col + 1).unwrap_or(false);
+/// Corners of the region at the given cell, judged from the 2×2 blocks the cell shares
+/// with its neighbours towards each diagonal.
+///
+/// With `a` and `b` the orthogonal neighbours and `d` the diagonal one, the cell has an
+/// outer corner there if neither `a` nor `b` belong to the region, and an inner corner if
+/// both do but `d` does not. Comparing region ids (not plants) keeps diagonal touches and
+/// enclosed regions apart.
+fn corners_at(comp_id: i32, map: &ComponentMap, row: i32, col: i32) -> usize {
+    let in_region = |r, c| has_id_at(comp_id, map, r, c).unwrap_or(false);
+    [(-1, -1), (-1, 1), (1, -1), (1, 1)]
+        .into_iter()
+        .filter(|(d_row, d_col)| {
+            let a = in_region(row + d_row, col);
+            let b = in_region(row, col + d_col);
+            let d = in_region(row + d_row, col + d_col);
+            (!a && !b) || (a && b && !d)
+        })
+        .count()
+}
 
-            let mut count_corners = 0;
+/// Number of corners of the region, which equals its number of sides.
+fn count_corners((comp_id, comp): &Component, map: &ComponentMap) -> usize {
+    comp.iter()
+        .map(|(row, col)| corners_at(*comp_id, map, *row, *col))
+        .sum()
+}
 
-            if top && left && !has_id_at(*comp_id, map, row - 1, col - 1).is_some_and(|v| v) {
-                count_corners += 1;
-            }
-            if top && right && !has_id_at(*comp_id, map, row - 1, col + 1).is_some_and(|v| v) {
-                count_corners += 1;
-            }
-            if bot && left && !has_id_at(*comp_id, map, row + 1, col - 1).is_some_and(|v| v) {
-                count_corners += 1;
-            }
-            if bot && right && !has_id_at(*comp_id, map, row + 1, col + 1).is_some_and(|v| v) {
-                count_corners += 1;
-            }
-            // if (bot && left && !top && !right)
-            // || (bot && right && !top && !left)
-            // || ()
-            // {
-            //     count_corners += 1;
-            // }
-
-            let connected_com_count = [top, bot, left, right].iter().filter(|v| **v).count();
-            if connected_com_count == 2 && (!(top && bot) && !(left && right)) {
-                count_corners += 1;
-            }
-            if connected_com_count == 1 {
-                count_corners += 2;
-            } else if connected_com_count == 0 {
-                count_corners += 4;
-            }
+/// Number of cell edges that border another region or the edge of the map.
+fn perimeter_of((comp_id, comp): &Component, map: &ComponentMap) -> usize {
+    comp.iter()
+        .flat_map(|(row, col)| {
+            [
+                (row - 1, *col),
+                (row + 1, *col),
+                (*row, col - 1),
+                (*row, col + 1),
+            ]
+        })
+        .filter(|(r, c)| !has_id_at(*comp_id, map, *r, *c).unwrap_or(false))
+        .count()
+}
+
+#[derive(Debug)]
+struct RegionStats {
+    plant: char,
+    area: usize,
+    perimeter: usize,
+    sides: usize,
+}
+
+impl RegionStats {
+    fn of(component: &Component, map: &Map, map_with_components: &ComponentMap) -> Self {
+        let (row, col) = component.1[0];
+        Self {
+            plant: map[row as usize][col as usize],
+            area: component.1.len(),
+            perimeter: perimeter_of(component, map_with_components),
+            sides: count_corners(component, map_with_components),
+        }
+    }
+
+    /// Part 1: area * perimeter.
+    fn perimeter_price(&self) -> usize {
+        self.area * self.perimeter
+    }
+
+    /// Part 2, with bulk discount: area * number of sides.
+    fn side_price(&self) -> usize {
+        self.area * self.sides
+    }
+}
+
+fn print_region_table(stats: &[RegionStats]) {
+    println!(
+        "{:>5} {:>6} {:>9} {:>6} {:>15} {:>10}",
+        "plant", "area", "perimeter", "sides", "perimeter price", "side price"
+    );
+    for s in stats {
+        println!(
+            "{:>5} {:>6} {:>9} {:>6} {:>15} {:>10}",
+            s.plant,
+            s.area,
+            s.perimeter,
+            s.sides,
+            s.perimeter_price(),
+            s.side_price()
+        );
+    }
+}
 
-            println!("got {count_corners} corners at {row} {col}");
-            count_corners
+/// Pseudo-random garden of `size`×`size` plots with four plant types. Runs of equal
+/// plants keep the regions from being tiny.
+fn random_map(size: usize) -> Map {
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    (0..size)
+        .map(|_| {
+            let mut plant = 'A';
+            (0..size)
+                .map(|_| {
+                    if next() % 3 == 0 {
+                        plant = (b'A' + (next() % 4) as u8) as char;
+                    }
+                    plant
+                })
+                .collect()
         })
-        .sum();
+        .collect()
+}
+
+fn bench(size: usize) {
+    let map = random_map(size);
+
+    let started = Instant::now();
+    let (components, labels) = find_connected_components(&map);
+    println!(
+        "find_connected_components       {:>10.3?} regions {}",
+        started.elapsed(),
+        components.len()
+    );
 
+    // The quadratic version takes hours on large maps.
+    if size > 200 {
+        println!("find_connected_components_naive skipped for size > 200");
+        return;
+    }
+    let started = Instant::now();
+    let (components, labels_naive) = find_connected_components_naive(&map);
     println!(
-        "{} * {} = {}",
-        count_corners,
-        comp.len(),
-        count_corners as usize * comp.len()
+        "find_connected_components_naive {:>10.3?} regions {}",
+        started.elapsed(),
+        components.len()
     );
-    count_corners as usize * comp.len()
+    assert_eq!(labels, labels_naive);
 }
 
 fn main() {
-    let mut map = read_file_to_map(&std::env::args().nth(1).unwrap()).unwrap();
-    let (components, map_with_components) = find_connected_components(&mut map);
-    print_grid(&map_with_components);
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|a| a == "--bench") {
+        bench(args.get(i + 1).map_or(1000, |s| s.parse().unwrap()));
+        return;
+    }
+
+    let map = read_file_to_map(&args[1]).unwrap();
+    let (components, map_with_components) = find_connected_components(&map);
+
+    // Colours only make sense on a terminal; NO_COLOR (https://no-color.org) or
+    // --no-color switch them off.
+    let colour = !args.iter().any(|a| a == "--no-color")
+        && std::env::var_os("NO_COLOR").is_none()
+        && io::stdout().is_terminal();
+    let colours = colour.then(|| region_colours(&map_with_components, components.len()));
+    if args.iter().any(|a| a == "--fences") {
+        println!(
+            "{}",
+            render_fences(&map, &map_with_components, colours.as_deref())
+        );
+    } else if args.iter().any(|a| a == "--render") {
+        println!(
+            "{}",
+            render_regions(&map, &map_with_components, colours.as_deref())
+        );
+    }
+
+    let stats: Vec<RegionStats> = components
+        .iter()
+        .map(|c| RegionStats::of(c, &map, &map_with_components))
+        .collect();
+    print_region_table(&stats);
     println!(
-        "{}",
-        components
+        "total perimeter price {}",
+        stats
             .iter()
-            .map(|c| cost_of(c, &map_with_components))
+            .map(RegionStats::perimeter_price)
             .sum::<usize>()
     );
+    println!(
+        "total side price {}",
+        stats.iter().map(RegionStats::side_price).sum::<usize>()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region_stats(garden: &str) -> Vec<RegionStats> {
+        let map: Map = garden.lines().map(|line| line.chars().collect()).collect();
+        let (components, map_with_components) = find_connected_components(&map);
+        components
+            .iter()
+            .map(|c| RegionStats::of(c, &map, &map_with_components))
+            .collect()
+    }
+
+    fn total_side_price(garden: &str) -> usize {
+        region_stats(garden)
+            .iter()
+            .map(RegionStats::side_price)
+            .sum()
+    }
+
+    #[test]
+    fn e_example() {
+        assert_eq!(total_side_price("EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE"), 236);
+    }
+
+    #[test]
+    fn xo_example() {
+        assert_eq!(total_side_price("OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO"), 436);
+    }
+
+    #[test]
+    fn ab_example() {
+        let garden = "AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA";
+        assert_eq!(total_side_price(garden), 368);
+    }
+
+    #[test]
+    fn enclosed_region() {
+        let stats = region_stats("AAAA\nABBA\nABBA\nAAAA");
+        assert_eq!(stats.len(), 2);
+        // Outer ring: 4 sides outside and 4 around the hole.
+        assert_eq!(
+            (stats[0].plant, stats[0].area, stats[0].sides),
+            ('A', 12, 8)
+        );
+        assert_eq!((stats[1].plant, stats[1].area, stats[1].sides), ('B', 4, 4));
+    }
+
+    #[test]
+    fn checkerboard_diagonals_are_separate_regions() {
+        let stats = region_stats("ABA\nBAB\nABA");
+        assert_eq!(stats.len(), 9);
+        assert!(stats
+            .iter()
+            .all(|s| (s.area, s.perimeter, s.sides) == (1, 4, 4)));
+    }
+
+    #[test]
+    fn neighbouring_regions_get_different_colours() {
+        let map = random_map(60);
+        let (components, map_with_components) = find_connected_components(&map);
+        let colours = region_colours(&map_with_components, components.len());
+        let size = map.len() as i32;
+        for (r, c) in (0..size).flat_map(|r| (0..size).map(move |c| (r, c))) {
+            let a = map_with_components[r as usize][c as usize];
+            for (r_next, c_next) in [(r + 1, c), (r, c + 1)] {
+                if let Some(b) = region_id_at(&map_with_components, r_next, c_next) {
+                    assert!(a == b || colours[a as usize] != colours[b as usize]);
+                }
+            }
+        }
+    }
 }
-//  A
-//  AA
-//   x
-//  AAx
-//   A
-//
-//
-//  A  AA  A
-//         A
-//
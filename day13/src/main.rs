@@ -1,12 +1,9 @@
-use itertools::Itertools;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use regex::Regex;
 use std::fs::File;
 use std::io::{self, BufRead};
 
 #[derive(Debug)]
 struct Button {
-    name: String,
     x: isize,
     y: isize,
 }
@@ -24,137 +21,392 @@ struct InputBlock {
     p: Prize,
 }
 
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
 fn parse_input(file_path: &str) -> io::Result<Vec<InputBlock>> {
     let file = File::open(file_path)?;
-    let reader = io::BufReader::new(file);
-
-    let mut blocks = Vec::new();
+    parse_blocks(io::BufReader::new(file))
+}
 
+/// Parses blocks of `Button A`, `Button B` and `Prize` lines, in that order, separated by
+/// empty lines. All malformed blocks are reported together, each with its line number.
+fn parse_blocks(reader: impl BufRead) -> io::Result<Vec<InputBlock>> {
     // Regex patterns for parsing button and prize
-    let button_re = Regex::new(r"Button (\w): X([+-]?\d+), Y([+-]?\d+)").unwrap();
-    let prize_re = Regex::new(r"Prize: X=([+-]?\d+), Y=([+-]?\d+)").unwrap();
-
-    let mut current_block = InputBlock {
-        a: Button {
-            name: String::new(),
-            x: 0,
-            y: 0,
-        },
-        b: Button {
-            name: String::new(),
-            x: 0,
-            y: 0,
-        },
-        p: Prize { x: 0, y: 0 },
-    };
+    let button_re = Regex::new(r"^Button ([AB]): X([+-]?\d+), Y([+-]?\d+)$").unwrap();
+    let prize_re = Regex::new(r"^Prize: X=([+-]?\d+), Y=([+-]?\d+)$").unwrap();
 
-    // Process each line in the file
-    for line in reader.lines() {
-        let line = line?;
+    let mut blocks = Vec::new();
+    let mut errors = Vec::new();
+    // Lines of the current block with their line numbers.
+    let mut current_block = Vec::<(usize, String)>::new();
 
-        // Skip empty lines (this will separate blocks)
-        if line.trim().is_empty() {
-            // If the current block is not empty, save it and reset
-            if !current_block.a.name.is_empty()
-                && !current_block.b.name.is_empty()
-                && current_block.p.x != 0
-                && current_block.p.y != 0
-            {
-                blocks.push(current_block);
+    let parse_block = |lines: &[(usize, String)]| -> Result<InputBlock, String> {
+        let expected = ["Button A", "Button B", "Prize"];
+        let line_at = |i: usize| {
+            lines.get(i).ok_or_else(|| {
+                format!(
+                    "block starting at line {} is missing its {} line",
+                    lines[0].0, expected[i]
+                )
+            })
+        };
+        let parse = |line_nr: usize, v: &str| {
+            v.parse::<isize>()
+                .map_err(|e| format!("line {}: invalid number {:?}: {}", line_nr, v, e))
+        };
+        let button = |i: usize, name: &str| {
+            let (line_nr, line) = line_at(i)?;
+            match button_re.captures(line) {
+                Some(caps) if &caps[1] == name => Ok(Button {
+                    x: parse(*line_nr, &caps[2])?,
+                    y: parse(*line_nr, &caps[3])?,
+                }),
+                _ => Err(format!(
+                    "line {}: expected \"Button {}: X+.., Y+..\", got {:?}",
+                    line_nr, name, line
+                )),
             }
+        };
 
-            // Reset the current block for the next set of data
-            current_block = InputBlock {
-                a: Button {
-                    name: String::new(),
-                    x: 0,
-                    y: 0,
-                },
-                b: Button {
-                    name: String::new(),
-                    x: 0,
-                    y: 0,
-                },
-                p: Prize { x: 0, y: 0 },
-            };
-
-            continue; // Skip processing the empty line
+        let a = button(0, "A")?;
+        let b = button(1, "B")?;
+        let (line_nr, line) = line_at(2)?;
+        let p = match prize_re.captures(line) {
+            Some(caps) => Prize {
+                x: parse(*line_nr, &caps[1])?,
+                y: parse(*line_nr, &caps[2])?,
+            },
+            None => {
+                return Err(format!(
+                    "line {}: expected \"Prize: X=.., Y=..\", got {:?}",
+                    line_nr, line
+                ))
+            }
+        };
+        if let Some((line_nr, line)) = lines.get(3) {
+            return Err(format!(
+                "line {}: unexpected {:?} after the prize, blocks must be separated by an empty line",
+                line_nr, line
+            ));
         }
+        Ok(InputBlock { a, b, p })
+    };
 
-        // Check for Button match
-        if let Some(caps) = button_re.captures(&line) {
-            let name = caps[1].to_string();
-            let x: isize = caps[2].parse().unwrap();
-            let y: isize = caps[3].parse().unwrap();
-
-            if name == "A" {
-                current_block.a = Button { name, x, y };
-            } else if name == "B" {
-                current_block.b = Button { name, x, y };
+    let mut finish_block = |lines: &mut Vec<(usize, String)>| {
+        if !lines.is_empty() {
+            match parse_block(lines) {
+                Ok(block) => blocks.push(block),
+                Err(e) => errors.push(e),
             }
+            lines.clear();
         }
-        // Check for Prize match
-        else if let Some(caps) = prize_re.captures(&line) {
-            let x: isize = caps[1].parse().unwrap();
-            let y: isize = caps[2].parse().unwrap();
-            current_block.p = Prize { x, y };
+    };
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            finish_block(&mut current_block);
+        } else {
+            current_block.push((i + 1, line.trim_end().to_string()));
         }
     }
+    finish_block(&mut current_block);
 
-    // Add the last block if not empty
-    if !current_block.a.name.is_empty()
-        && !current_block.b.name.is_empty()
-        && current_block.p.x != 0
-        && current_block.p.y != 0
-    {
-        blocks.push(current_block);
+    if !errors.is_empty() {
+        return Err(invalid_data(errors.join("\n")));
     }
-
     Ok(blocks)
 }
 
-// fn get_token_cost_to_win(block: &InputBlock) -> i32 {
-//     (0..=100)
-//         .cartesian_product(0..=100)
-//         .filter(|(a, b)| {
-//             let x = block.a.x * a + block.b.x * b;
-//             let y = block.a.y * a + block.b.y * b;
-//             x == block.p.x && y == block.p.y
-//         })
-//         .map(|(a, b)| a * 3 + b)
-//         .min()
-//         .unwrap_or(0)
-// }
+/// Presses of button A and B and the token cost of winning a prize.
+type Solution = (isize, isize, isize);
+
+const PART_1_MAX_PRESSES: isize = 100;
+const PART_2_PRIZE_OFFSET: isize = 10000000000000;
+
+/// Token cost per press of each button and an optional limit on the presses per button.
+#[derive(Debug, Clone, Copy)]
+struct SolverConfig {
+    cost_a: isize,
+    cost_b: isize,
+    max_presses: Option<isize>,
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        Self {
+            cost_a: 3,
+            cost_b: 1,
+            max_presses: None,
+        }
+    }
+}
+
+impl SolverConfig {
+    fn token_cost(&self, a: isize, b: isize) -> isize {
+        a * self.cost_a + b * self.cost_b
+    }
+
+    fn allows(&self, a: isize, b: isize) -> bool {
+        a >= 0 && b >= 0 && self.max_presses.is_none_or(|max| a <= max && b <= max)
+    }
+}
+
+/// Returns `(g, x, y)` with `a * x + b * y == g == gcd(a, b)`.
+fn extended_gcd(a: isize, b: isize) -> (isize, isize, isize) {
+    if b == 0 {
+        return (a.abs(), a.signum(), 0);
+    }
+    let (g, x, y) = extended_gcd(b, a % b);
+    (g, y, x - (a / b) * y)
+}
+
+/// Bounds `(lower, upper)` on `k` such that `offset + k * coefficient >= 0`, or `None` if
+/// no `k` satisfies it.
+fn non_negative_range(coefficient: isize, offset: isize) -> Option<(Option<isize>, Option<isize>)> {
+    match coefficient.signum() {
+        0 => (offset >= 0).then_some((None, None)),
+        1 => Some((Some(-offset.div_euclid(coefficient)), None)),
+        _ => Some((None, Some(offset.div_euclid(-coefficient)))),
+    }
+}
+
+/// Cheapest allowed `a`, `b` with `a * ax + b * bx == p`, if any.
+fn solve_on_line(ax: isize, bx: isize, p: isize, config: &SolverConfig) -> Option<Solution> {
+    let (g, x, y) = extended_gcd(ax, bx);
+    if g == 0 {
+        return (p == 0).then_some((0, 0, 0));
+    }
+    if p % g != 0 {
+        return None;
+    }
+    // All solutions: a = a0 + k * step_a, b = b0 - k * step_b.
+    let (a0, b0) = (x * (p / g), y * (p / g));
+    let (step_a, step_b) = (bx / g, ax / g);
+
+    // Bounds on k that keep a and b non-negative and within the press limit.
+    let mut constraints = vec![(step_a, a0), (-step_b, b0)];
+    if let Some(max) = config.max_presses {
+        constraints.extend([(-step_a, max - a0), (step_b, max - b0)]);
+    }
+    let mut lower = None;
+    let mut upper = None;
+    for (coefficient, offset) in constraints {
+        let (l, u) = non_negative_range(coefficient, offset)?;
+        lower = lower.into_iter().chain(l).max();
+        upper = upper.into_iter().chain(u).min();
+    }
+
+    // The cost is linear in k, so the cheapest solution is at one end of the range.
+    let slope = config.token_cost(step_a, -step_b);
+    let k = match (lower, upper) {
+        (Some(l), Some(u)) if l > u => return None,
+        (Some(l), _) if slope >= 0 => l,
+        (_, Some(u)) if slope <= 0 => u,
+        (Some(l), None) => l,
+        (None, Some(u)) => u,
+        _ => 0,
+    };
+    let (a, b) = (a0 + k * step_a, b0 - k * step_b);
+    Some((a, b, config.token_cost(a, b)))
+}
 
-fn get_token_cost_to_win_with_math(block: &mut InputBlock) -> isize {
-    block.p.x += 10000000000000;
-    block.p.y += 10000000000000;
+/// Cheapest way to win the prize of `block`, moved by `prize_offset` along both axes.
+fn solve(block: &InputBlock, prize_offset: isize, config: &SolverConfig) -> Option<Solution> {
+    let (ax, ay, bx, by) = (block.a.x, block.a.y, block.b.x, block.b.y);
+    let (px, py) = (block.p.x + prize_offset, block.p.y + prize_offset);
 
-    // - ax*by*B + ay*bx*B = px*ay-ax*py
-    let b_divisor = -block.a.x * block.b.y + block.a.y * block.b.x;
-    let b_divident = block.p.x * block.a.y - block.a.x * block.p.y;
-    if b_divident % b_divisor != 0 {
-        return 0;
+    let det = ax * by - ay * bx;
+    if det != 0 {
+        let a_dividend = px * by - py * bx;
+        let b_dividend = ax * py - ay * px;
+        if a_dividend % det != 0 || b_dividend % det != 0 {
+            return None;
+        }
+        let (a, b) = (a_dividend / det, b_dividend / det);
+        return config.allows(a, b).then(|| (a, b, config.token_cost(a, b)));
     }
-    let b = b_divident / b_divisor;
 
-    // A = (py - by*B)/ay
-    if (block.p.y - block.b.y * b) % block.a.y != 0 {
-        return 0;
+    // Both buttons move along the same line; the prize must lie on it as well.
+    if ax * py - ay * px != 0 || bx * py - by * px != 0 {
+        return None;
     }
-    let a = (block.p.y - block.b.y * b) / block.a.y;
-    println!("solution: A={}, B={}", a, b);
-    a * 3 + b
+    let solution = if ax != 0 || bx != 0 {
+        solve_on_line(ax, bx, px, config)?
+    } else {
+        solve_on_line(ay, by, py, config)?
+    };
+    let (a, b, _) = solution;
+    (a * ax + b * bx == px && a * ay + b * by == py).then_some(solution)
+}
+
+fn total_cost(blocks: &[InputBlock], prize_offset: isize, config: &SolverConfig) -> isize {
+    blocks
+        .iter()
+        .filter_map(|block| solve(block, prize_offset, config))
+        .map(|(_, _, cost)| cost)
+        .sum()
 }
 
 fn main() {
-    // Example file path, replace it with the actual path
-    let mut blocks = parse_input(&std::env::args().nth(1).unwrap()).unwrap();
-    println!(
-        "{}",
-        blocks
-            .iter_mut()
-            .map(get_token_cost_to_win_with_math)
-            .sum::<isize>()
-    );
+    let args: Vec<String> = std::env::args().collect();
+    let blocks = parse_input(&args[1]).unwrap_or_else(|e| panic!("{}:\n{}", args[1], e));
+    let value_of = |flag: &str| {
+        args.iter().position(|a| a == flag).map(|i| {
+            args.get(i + 1)
+                .and_then(|v| v.parse::<isize>().ok())
+                .unwrap_or_else(|| panic!("Usage: {} <number>", flag))
+        })
+    };
+    let part = value_of("--part");
+
+    let mut config = SolverConfig::default();
+    if let Some(cost) = value_of("--cost-a") {
+        config.cost_a = cost;
+    }
+    if let Some(cost) = value_of("--cost-b") {
+        config.cost_b = cost;
+    }
+    config.max_presses = value_of("--max-presses");
+
+    if matches!(part, None | Some(1)) {
+        let config = SolverConfig {
+            max_presses: config.max_presses.or(Some(PART_1_MAX_PRESSES)),
+            ..config
+        };
+        println!("part 1: {}", total_cost(&blocks, 0, &config));
+    }
+    if matches!(part, None | Some(2)) {
+        println!(
+            "part 2: {}",
+            total_cost(&blocks, PART_2_PRIZE_OFFSET, &config)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(a: (isize, isize), b: (isize, isize), prize: (isize, isize)) -> InputBlock {
+        InputBlock {
+            a: Button { x: a.0, y: a.1 },
+            b: Button { x: b.0, y: b.1 },
+            p: Prize {
+                x: prize.0,
+                y: prize.1,
+            },
+        }
+    }
+
+    fn example() -> Vec<InputBlock> {
+        vec![
+            machine((94, 34), (22, 67), (8400, 5400)),
+            machine((26, 66), (67, 21), (12748, 12176)),
+            machine((17, 86), (84, 37), (7870, 6450)),
+            machine((69, 23), (27, 71), (18641, 10279)),
+        ]
+    }
+
+    const VALID_BLOCK: &str = "\
+Button A: X+94, Y+34
+Button B: X+22, Y+67
+Prize: X=8400, Y=5400
+";
+
+    /// The error message of parsing `VALID_BLOCK` followed by `block`, which starts at line 5.
+    fn parse_error(block: &str) -> String {
+        let input = format!("{}\n{}", VALID_BLOCK, block);
+        let error = parse_blocks(input.as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        error.to_string()
+    }
+
+    #[test]
+    fn prize_on_an_axis_is_kept() {
+        let input = "Button A: X+94, Y+34\nButton B: X+22, Y+67\nPrize: X=0, Y=5400\n";
+        let blocks = parse_blocks(input.as_bytes()).unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!((blocks[0].p.x, blocks[0].p.y), (0, 5400));
+    }
+
+    #[test]
+    fn missing_button_b_is_reported() {
+        let error = parse_error("Button A: X+1, Y+2\nPrize: X=3, Y=4\n");
+        assert!(
+            error.starts_with("line 6: expected \"Button B"),
+            "{}",
+            error
+        );
+
+        let error = parse_error("Button A: X+1, Y+2\n");
+        assert_eq!(
+            error,
+            "block starting at line 5 is missing its Button B line"
+        );
+    }
+
+    #[test]
+    fn swapped_buttons_are_reported() {
+        let error = parse_error("Button B: X+1, Y+2\nButton A: X+3, Y+4\nPrize: X=5, Y=6\n");
+        assert!(
+            error.starts_with("line 5: expected \"Button A"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn fourth_line_is_reported() {
+        let error = parse_error(&format!("{}Prize: X=5, Y=6\n", VALID_BLOCK));
+        assert!(error.starts_with("line 8: unexpected"), "{}", error);
+    }
+
+    #[test]
+    fn puzzle_example() {
+        let part_1 = SolverConfig {
+            max_presses: Some(PART_1_MAX_PRESSES),
+            ..SolverConfig::default()
+        };
+        assert_eq!(total_cost(&example(), 0, &part_1), 480);
+        assert_eq!(
+            total_cost(&example(), PART_2_PRIZE_OFFSET, &SolverConfig::default()),
+            875318608908
+        );
+    }
+
+    #[test]
+    fn press_limit_rules_out_exact_solution() {
+        let block = machine((94, 34), (22, 67), (8400, 5400));
+        let limit = |max| SolverConfig {
+            max_presses: Some(max),
+            ..SolverConfig::default()
+        };
+        assert_eq!(solve(&block, 0, &limit(80)), Some((80, 40, 280)));
+        assert_eq!(solve(&block, 0, &limit(79)), None);
+    }
+
+    #[test]
+    fn collinear_machine_picks_cheapest_end() {
+        // Both buttons move diagonally; B moves twice as far as A.
+        let block = machine((1, 1), (2, 2), (10, 10));
+        let config = SolverConfig::default();
+        assert_eq!(solve(&block, 0, &config), Some((0, 5, 5)));
+
+        let swapped_costs = SolverConfig {
+            cost_a: 1,
+            cost_b: 3,
+            ..config
+        };
+        assert_eq!(solve(&block, 0, &swapped_costs), Some((10, 0, 10)));
+
+        let limit = |max| SolverConfig {
+            max_presses: Some(max),
+            ..config
+        };
+        assert_eq!(solve(&block, 0, &limit(4)), Some((2, 4, 10)));
+        assert_eq!(solve(&block, 0, &limit(3)), None);
+    }
 }
@@ -1,8 +1,9 @@
 use itertools::Itertools;
 use regex::Regex;
-use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, BufRead};
+use std::ops::Range;
+use std::path::Path;
 
 #[derive(Debug, Clone, Copy)]
 struct PointVelocity {
@@ -12,8 +13,10 @@ struct PointVelocity {
 
 fn parse_input(file_path: &str) -> io::Result<Vec<PointVelocity>> {
     let file = File::open(file_path)?;
-    let reader = io::BufReader::new(file);
+    parse_robots(io::BufReader::new(file))
+}
 
+fn parse_robots(reader: impl BufRead) -> io::Result<Vec<PointVelocity>> {
     // Regex pattern for matching p and v
     let re = Regex::new(r"p=([+-]?\d+),([+-]?\d+) v=([+-]?\d+),([+-]?\d+)").unwrap();
 
@@ -41,26 +44,37 @@ fn parse_input(file_path: &str) -> io::Result<Vec<PointVelocity>> {
     Ok(result)
 }
 
-const WIDE: i32 = 101;
-const TALL: i32 = 103;
-// const WIDE: i32 = 11;
-// const TALL: i32 = 7;
+/// Size of the area the robots move in; they wrap around at its edges.
+#[derive(Debug, Clone, Copy)]
+struct Arena {
+    wide: i32,
+    tall: i32,
+}
 
-fn move_robot(r: &PointVelocity, sec: i32) -> PointVelocity {
+impl Default for Arena {
+    fn default() -> Self {
+        Self {
+            wide: 101,
+            tall: 103,
+        }
+    }
+}
+
+fn move_robot(r: &PointVelocity, sec: i32, arena: Arena) -> PointVelocity {
     PointVelocity {
         p: (
-            (r.p.0 + r.v.0 * sec).rem_euclid(WIDE),
-            (r.p.1 + r.v.1 * sec).rem_euclid(TALL),
+            (r.p.0 + r.v.0 * sec).rem_euclid(arena.wide),
+            (r.p.1 + r.v.1 * sec).rem_euclid(arena.tall),
         ),
         v: r.v,
     }
 }
 
-fn get_quadrant(r: &PointVelocity) -> Option<i32> {
-    if r.p.0 == (WIDE / 2) || r.p.1 == (TALL / 2) {
+fn get_quadrant(r: &PointVelocity, arena: Arena) -> Option<i32> {
+    if r.p.0 == (arena.wide / 2) || r.p.1 == (arena.tall / 2) {
         return None;
     }
-    Some(match (r.p.0 > (WIDE / 2), r.p.1 > (TALL / 2)) {
+    Some(match (r.p.0 > (arena.wide / 2), r.p.1 > (arena.tall / 2)) {
         (true, true) => 4,
         (true, false) => 3,
         (false, true) => 2,
@@ -68,9 +82,80 @@ fn get_quadrant(r: &PointVelocity) -> Option<i32> {
     })
 }
 
+/// Product of the robot counts per quadrant; robots on the middle lines do not count.
+fn safety_factor(robots: &[PointVelocity], arena: Arena) -> usize {
+    let mut counts = [0_usize; 4];
+    for quadrant in robots.iter().filter_map(|r| get_quadrant(r, arena)) {
+        counts[quadrant as usize - 1] += 1;
+    }
+    counts.iter().product()
+}
+
+fn variance(values: impl Iterator<Item = i32> + Clone) -> f64 {
+    let count = values.clone().count() as f64;
+    let mean = values.clone().map(f64::from).sum::<f64>() / count;
+    values.map(|v| (f64::from(v) - mean).powi(2)).sum::<f64>() / count
+}
+
+/// The second in `0..period` at which the given coordinate of all robots is least spread
+/// out. Each coordinate repeats with the arena size along its axis, so this is the offset
+/// at which the robots line up along that axis.
+fn least_spread_offset(
+    robots: &[PointVelocity],
+    arena: Arena,
+    period: i32,
+    coordinate: impl Fn(&PointVelocity) -> i32,
+) -> i32 {
+    let spread = |sec: i32| {
+        variance(
+            robots
+                .iter()
+                .map(|r| coordinate(&move_robot(r, sec, arena))),
+        )
+    };
+    (0..period)
+        .min_by(|a, b| spread(*a).total_cmp(&spread(*b)))
+        .unwrap()
+}
+
+/// Returns `(g, x, y)` with `a * x + b * y == g == gcd(a, b)`.
+fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        return (a, 1, 0);
+    }
+    let (g, x, y) = extended_gcd(b, a % b);
+    (g, y, x - (a / b) * y)
+}
+
+/// Smallest `t >= 0` with `t ≡ a1 (mod n1)` and `t ≡ a2 (mod n2)` (Chinese Remainder
+/// Theorem), or `None` if the two congruences contradict each other.
+fn crt(a1: i64, n1: i64, a2: i64, n2: i64) -> Option<i64> {
+    let (g, x, _) = extended_gcd(n1, n2);
+    if (a2 - a1) % g != 0 {
+        return None;
+    }
+    let lcm = n1 / g * n2;
+    let t = a1 + (a2 - a1) / g * x % (n2 / g) * n1;
+    Some(t.rem_euclid(lcm))
+}
+
+/// The tree appears when the robots cluster along both axes at once. Each axis is
+/// periodic, so the x and y offsets of least variance are combined with the CRT.
+fn find_christmas_tree(robots: &[PointVelocity], arena: Arena) -> Option<i32> {
+    let x_offset = least_spread_offset(robots, arena, arena.wide, |r| r.p.0);
+    let y_offset = least_spread_offset(robots, arena, arena.tall, |r| r.p.1);
+    crt(
+        x_offset.into(),
+        arena.wide.into(),
+        y_offset.into(),
+        arena.tall.into(),
+    )
+    .map(|t| t as i32)
+}
+
 // Function to print the points on a 2D plane
-fn print_points_on_plane(points: &Vec<PointVelocity>) {
-    let mut grid = vec![vec!['.'; WIDE as usize]; TALL as usize];
+fn print_points_on_plane(points: &[PointVelocity], arena: Arena) {
+    let mut grid = vec![vec!['.'; arena.wide as usize]; arena.tall as usize];
 
     // Step 3: Plot the points on the grid
     for point in points {
@@ -83,29 +168,222 @@ fn print_points_on_plane(points: &Vec<PointVelocity>) {
     }
 }
 
+/// Black-and-white image, `true` marks a black pixel.
+struct Bitmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    fn of_robots(robots: &[PointVelocity], arena: Arena) -> Self {
+        let mut bitmap = Self::new(arena.wide as usize, arena.tall as usize);
+        for r in robots {
+            bitmap.set(r.p.0 as usize, r.p.1 as usize);
+        }
+        bitmap
+    }
+
+    fn set(&mut self, x: usize, y: usize) {
+        self.pixels[y * self.width + x] = true;
+    }
+
+    /// Tiles the frames row by row, `columns` per row, separated by 1 pixel black lines.
+    fn contact_sheet(frames: &[Bitmap], columns: usize) -> Self {
+        let (tile_width, tile_height) = (frames[0].width + 1, frames[0].height + 1);
+        let rows = frames.len().div_ceil(columns);
+        let mut sheet = Self::new(columns * tile_width - 1, rows * tile_height - 1);
+        for y in 0..sheet.height {
+            for x in 0..sheet.width {
+                let (column, tile_x) = (x / tile_width, x % tile_width);
+                let (row, tile_y) = (y / tile_height, y % tile_height);
+                let is_separator = tile_x == tile_width - 1 || tile_y == tile_height - 1;
+                let black = is_separator
+                    || frames
+                        .get(row * columns + column)
+                        .is_some_and(|frame| frame.pixels[tile_y * frame.width + tile_x]);
+                if black {
+                    sheet.set(x, y);
+                }
+            }
+        }
+        sheet
+    }
+
+    /// Binary portable bitmap (`P4`): 8 pixels per byte, rows padded to whole bytes.
+    fn to_pbm(&self) -> Vec<u8> {
+        let mut data = format!("P4\n{} {}\n", self.width, self.height).into_bytes();
+        for row in self.pixels.chunks(self.width) {
+            for byte in row.chunks(8) {
+                data.push(
+                    byte.iter()
+                        .enumerate()
+                        .fold(0, |acc, (i, black)| acc | (u8::from(*black) << (7 - i))),
+                );
+            }
+        }
+        data
+    }
+
+    #[cfg(feature = "png")]
+    fn to_png(&self) -> Vec<u8> {
+        let mut data = vec![];
+        let mut encoder = png::Encoder::new(&mut data, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|black| if *black { 0 } else { 255 })
+            .collect_vec();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+        data
+    }
+
+    fn encode(&self, format: &str) -> Vec<u8> {
+        match format {
+            "pbm" => self.to_pbm(),
+            #[cfg(feature = "png")]
+            "png" => self.to_png(),
+            #[cfg(not(feature = "png"))]
+            "png" => panic!("PNG output needs the `png` feature"),
+            other => panic!("Usage: --format <pbm|png>, got {:?}", other),
+        }
+    }
+}
+
+/// Parses `START..END` (end exclusive) or `START..=END`.
+fn parse_frame_range(range: &str) -> Option<Range<i32>> {
+    let (start, end) = range.split_once("..")?;
+    let start = start.parse().ok()?;
+    match end.strip_prefix('=') {
+        Some(end) => Some(start..end.parse::<i32>().ok()? + 1),
+        None => Some(start..end.parse().ok()?),
+    }
+}
+
+/// Writes one image per second in `frames`, and optionally a contact sheet of all of them.
+fn export_frames(
+    robots: &[PointVelocity],
+    arena: Arena,
+    frames: Range<i32>,
+    out_dir: &Path,
+    format: &str,
+    contact_sheet_columns: Option<usize>,
+) -> io::Result<()> {
+    fs::create_dir_all(out_dir)?;
+    let mut bitmaps = vec![];
+    for sec in frames.clone() {
+        let moved = robots
+            .iter()
+            .map(|r| move_robot(r, sec, arena))
+            .collect_vec();
+        let bitmap = Bitmap::of_robots(&moved, arena);
+        fs::write(
+            out_dir.join(format!("frame_{:05}.{}", sec, format)),
+            bitmap.encode(format),
+        )?;
+        if contact_sheet_columns.is_some() {
+            bitmaps.push(bitmap);
+        }
+    }
+
+    if let Some(columns) = contact_sheet_columns.filter(|_| !bitmaps.is_empty()) {
+        let sheet = Bitmap::contact_sheet(&bitmaps, columns);
+        let name = format!(
+            "contact_sheet_{:05}_{:05}.{}",
+            frames.start,
+            frames.end - 1,
+            format
+        );
+        fs::write(out_dir.join(name), sheet.encode(format))?;
+    }
+    println!("wrote {} frames to {}", frames.len(), out_dir.display());
+    Ok(())
+}
+
 fn main() {
-    // Example file path, replace it with the actual path
-    let file_path = std::env::args().nth(1).unwrap();
+    let args: Vec<String> = std::env::args().collect();
+    let file_path = &args[1];
+    let value_of = |flag: &str| {
+        args.iter().position(|a| a == flag).map(|i| {
+            args.get(i + 1)
+                .and_then(|v| v.parse::<i32>().ok())
+                .unwrap_or_else(|| panic!("Usage: {} <number>", flag))
+        })
+    };
 
-    let robots = parse_input(&file_path).unwrap();
+    let default = Arena::default();
+    let arena = Arena {
+        wide: value_of("--width").unwrap_or(default.wide),
+        tall: value_of("--height").unwrap_or(default.tall),
+    };
+    let part = value_of("--part");
 
-    let mut cur_min = 100000000000_usize;
-    for x in 1..10000 {
-        let moved = robots.iter().map(|r| move_robot(r, x)).collect_vec();
-        let danger = moved
+    let robots = parse_input(file_path).unwrap();
+
+    let string_value_of = |flag: &str| {
+        args.iter().position(|a| a == flag).map(|i| {
+            args.get(i + 1)
+                .unwrap_or_else(|| panic!("missing value for {}", flag))
+        })
+    };
+    if let Some(frames) = string_value_of("--frames") {
+        let contact_sheet_columns = value_of("--contact-sheet").map(|columns| {
+            usize::try_from(columns)
+                .ok()
+                .filter(|c| *c > 0)
+                .unwrap_or_else(|| panic!("Usage: --contact-sheet <columns >= 1>, got {}", columns))
+        });
+        export_frames(
+            &robots,
+            arena,
+            parse_frame_range(frames)
+                .unwrap_or_else(|| panic!("Usage: --frames START..END, got {:?}", frames)),
+            Path::new(string_value_of("--out").map_or("frames", String::as_str)),
+            string_value_of("--format").map_or("pbm", String::as_str),
+            contact_sheet_columns,
+        )
+        .unwrap();
+        return;
+    }
+
+    if matches!(part, None | Some(1)) {
+        let seconds = value_of("--seconds").unwrap_or(100);
+        let moved = robots
             .iter()
-            .filter_map(get_quadrant)
-            .fold(HashMap::new(), |mut acc, item| {
-                *acc.entry(item).or_insert(0) += 1;
-                acc
-            })
-            .values()
-            .product::<i32>();
-        if (danger as usize) < cur_min {
-            cur_min = danger as usize;
-            println!("AFTER {} seconds", x);
-            print_points_on_plane(&moved);
+            .map(|r| move_robot(r, seconds, arena))
+            .collect_vec();
+        println!(
+            "safety factor after {} seconds: {}",
+            seconds,
+            safety_factor(&moved, arena)
+        );
+    }
+    if part.is_some_and(|p| p != 2) {
+        return;
+    }
+
+    match find_christmas_tree(&robots, arena) {
+        Some(seconds) => {
+            println!("christmas tree after {} seconds", seconds);
+            let moved = robots
+                .iter()
+                .map(|r| move_robot(r, seconds, arena))
+                .collect_vec();
+            print_points_on_plane(&moved, arena);
         }
+        None => println!("no christmas tree found"),
     }
 }
 
@@ -143,3 +421,111 @@ fn main() {
 // #.............................#
 // #.............................#
 // ###############################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+p=0,4 v=3,-3
+p=6,3 v=-1,-3
+p=10,3 v=-1,2
+p=2,0 v=2,-1
+p=0,0 v=1,3
+p=3,0 v=-2,-2
+p=7,6 v=-1,-3
+p=3,0 v=-1,-2
+p=9,3 v=2,3
+p=7,3 v=-1,2
+p=2,4 v=2,-3
+p=9,5 v=-3,-3
+";
+
+    #[test]
+    fn crt_combines_congruences() {
+        assert_eq!(crt(2, 3, 3, 5), Some(8));
+        assert_eq!(crt(0, 101, 0, 103), Some(0));
+        assert_eq!(crt(100, 101, 102, 103), Some(101 * 103 - 1));
+        // Moduli sharing a factor of 2.
+        assert_eq!(crt(1, 4, 3, 6), Some(9));
+        assert_eq!(crt(0, 4, 1, 6), None);
+    }
+
+    #[test]
+    fn christmas_tree_at_planted_second() {
+        const TREE_AT: i32 = 7777;
+        let arena = Arena::default();
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = move |n: i32| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % n as u64) as i32
+        };
+        // Robots clustered in a 20x20 square at TREE_AT, moved back to second 0.
+        let robots = (0..500)
+            .map(|_| {
+                let (x, y) = (40 + next(20), 40 + next(20));
+                let v = (next(201) - 100, next(201) - 100);
+                PointVelocity {
+                    p: (
+                        (x - v.0 * TREE_AT).rem_euclid(arena.wide),
+                        (y - v.1 * TREE_AT).rem_euclid(arena.tall),
+                    ),
+                    v,
+                }
+            })
+            .collect_vec();
+        assert_eq!(find_christmas_tree(&robots, arena), Some(TREE_AT));
+    }
+
+    fn bitmap_of(pixels: &str) -> Bitmap {
+        let rows = pixels.lines().collect_vec();
+        let mut bitmap = Bitmap::new(rows[0].len(), rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, _) in row.chars().enumerate().filter(|(_, c)| *c == '#') {
+                bitmap.set(x, y);
+            }
+        }
+        bitmap
+    }
+
+    #[test]
+    fn pbm_rows_are_padded_to_whole_bytes() {
+        let bitmap = bitmap_of("#.......#\n.#.......");
+        let mut expected = b"P4\n9 2\n".to_vec();
+        expected.extend([0b1000_0000, 0b1000_0000, 0b0100_0000, 0b0000_0000]);
+        assert_eq!(bitmap.to_pbm(), expected);
+    }
+
+    #[test]
+    fn contact_sheet_layout() {
+        let frames = [
+            bitmap_of("#.\n.."),
+            bitmap_of("..\n.#"),
+            bitmap_of("..\n#."),
+        ];
+        let sheet = Bitmap::contact_sheet(&frames, 2);
+        // Three 2x2 frames in two columns; the fourth tile stays empty.
+        let expected = bitmap_of(
+            "\
+#.#..
+..#.#
+#####
+..#..
+#.#..",
+        );
+        assert_eq!((sheet.width, sheet.height), (5, 5));
+        assert_eq!(sheet.pixels, expected.pixels);
+    }
+
+    #[test]
+    fn example_safety_factor() {
+        let robots = parse_robots(EXAMPLE.as_bytes()).unwrap();
+        assert_eq!(robots.len(), 12);
+
+        let arena = Arena { wide: 11, tall: 7 };
+        let moved: Vec<_> = robots.iter().map(|r| move_robot(r, 100, arena)).collect();
+        assert_eq!(safety_factor(&moved, arena), 12);
+    }
+}
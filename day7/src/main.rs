@@ -40,32 +40,84 @@ fn check_is_valid(line: &Line) -> bool {
         .map(|_| vec!['*', '+', '|'])
         .multi_cartesian_product()
         .any(|ops| {
-            let calculated = line.numbers[1..].iter().zip(ops).fold(
-                line.numbers[0],
-                |acc, (number, op)| match op {
-                    '*' => acc * number,
-                    '+' => acc + number,
-                    '|' => (acc.to_string() + &number.to_string())
-                        .parse::<i64>()
-                        .unwrap(),
-                    _ => panic!(),
-                },
-            );
-            calculated == line.test_value
+            // An overflowing intermediate result is deliberately treated as
+            // "cannot match", even if a later `* 0` would bring it back into range.
+            let calculated =
+                line.numbers[1..]
+                    .iter()
+                    .zip(ops)
+                    .try_fold(line.numbers[0], |acc, (number, op)| match op {
+                        '*' => acc.checked_mul(*number),
+                        '+' => acc.checked_add(*number),
+                        '|' => concat(acc, *number),
+                        _ => panic!(),
+                    });
+            calculated == Some(line.test_value)
         })
 }
 
+fn concat(lhs: i64, rhs: i64) -> Option<i64> {
+    let digits = rhs.checked_ilog10().unwrap_or(0) + 1;
+    10_i64
+        .checked_pow(digits)
+        .and_then(|shift| lhs.checked_mul(shift))
+        .and_then(|shifted| shifted.checked_add(rhs))
+}
+
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
     let file_path = &args[1];
 
     let lines = parse_file_to_lines(file_path);
 
-    let total: i64 = lines
+    let total: i128 = lines
         .iter()
         .filter(|l| check_is_valid(l))
-        .fold(0_i64, |acc, l| acc + l.test_value);
+        .fold(0_i128, |acc, l| acc + l.test_value as i128);
     println!("total {}", total);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concat_just_under_the_limit() {
+        assert_eq!(concat(922_337_203_685_477_580, 7), Some(i64::MAX));
+    }
+
+    #[test]
+    fn concat_overflow_is_none() {
+        assert_eq!(concat(922_337_203_685_477_580, 8), None);
+        assert_eq!(concat(i64::MAX, 1), None);
+    }
+
+    #[test]
+    fn overflowing_equation_cannot_match() {
+        let line = Line {
+            test_value: i64::MAX - 1,
+            numbers: vec![i64::MAX / 2 + 1, 2],
+        };
+        assert!(!check_is_valid(&line));
+    }
+
+    #[test]
+    fn overflow_is_not_rescued_by_multiplying_with_zero() {
+        let line = Line {
+            test_value: 0,
+            numbers: vec![i64::MAX, 2, 0],
+        };
+        assert!(!check_is_valid(&line));
+    }
+
+    #[test]
+    fn concat_up_to_the_limit_matches() {
+        let line = Line {
+            test_value: i64::MAX,
+            numbers: vec![922_337_203_685_477_580, 7],
+        };
+        assert!(check_is_valid(&line));
+    }
+}
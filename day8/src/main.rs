@@ -1,4 +1,3 @@
-use std::arch::x86_64::_mm256_and_pd;
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::env;
@@ -34,15 +33,29 @@ impl Pos {
             self.col + i * (self.col - other.col),
         )
     }
+
+    /// Smallest integer step along the line from `self` to `other`.
+    fn lattice_step_towards(self, other: Pos) -> Pos {
+        let (d_row, d_col) = (other.row - self.row, other.col - self.col);
+        let divisor = gcd(d_row, d_col).max(1);
+        Pos::new(d_row / divisor, d_col / divisor)
+    }
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
 }
 
 type AntennaPositions = HashMap<char, Vec<Pos>>;
 
-fn find_antennas(map: &Vec<Vec<char>>) -> AntennaPositions {
+fn find_antennas(map: &[Vec<char>]) -> AntennaPositions {
     let mut result = AntennaPositions::new();
-    for row in 0..map.len() {
-        for col in 0..map[row].len() {
-            let map_c = map[row][col];
+    for (row, line) in map.iter().enumerate() {
+        for (col, &map_c) in line.iter().enumerate() {
             if map_c == '.' {
                 continue;
             }
@@ -67,53 +80,309 @@ fn is_valid(pos: Pos, map: &[Vec<char>]) -> bool {
         || pos.col as usize >= map[0].len())
 }
 
-fn find_count_pos_with_antinode(
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum AntinodeMode {
+    /// Part 1: one antinode on each side of the pair, twice as far from one antenna as from the other.
+    Pair,
+    /// Part 2: every position in line with the pair, including the antennas themselves.
+    Harmonics,
+    /// Like `Harmonics`, but steps by the gcd-reduced difference so that grid points
+    /// between and beyond the antennas are not skipped.
+    Collinear,
+}
+
+fn antinodes_of_pair(a: Pos, b: Pos, mode: AntinodeMode, map: &[Vec<char>]) -> Vec<Pos> {
+    match mode {
+        AntinodeMode::Pair => [a.plus_difference(b, 1), b.plus_difference(a, 1)]
+            .into_iter()
+            .filter(|pos| is_valid(*pos, map))
+            .collect_vec(),
+        AntinodeMode::Harmonics => {
+            let walk = |from: Pos, away_from: Pos| {
+                (0..).map_while(move |i| {
+                    let pos = from.plus_difference(away_from, i);
+                    is_valid(pos, map).then_some(pos)
+                })
+            };
+            walk(a, b).chain(walk(b, a)).collect_vec()
+        }
+        AntinodeMode::Collinear => {
+            let step = a.lattice_step_towards(b);
+            let walk = |sign: i32| {
+                (0..).map_while(move |i| {
+                    let pos = Pos::new(a.row + sign * i * step.row, a.col + sign * i * step.col);
+                    is_valid(pos, map).then_some(pos)
+                })
+            };
+            walk(1).chain(walk(-1).skip(1)).collect_vec()
+        }
+    }
+}
+
+fn antinodes_of_frequency(
+    frequency_positions: &[Pos],
+    map: &[Vec<char>],
+    mode: AntinodeMode,
+) -> HashSet<Pos> {
+    frequency_positions
+        .iter()
+        .tuple_combinations()
+        .flat_map(|(a, b)| antinodes_of_pair(*a, *b, mode, map))
+        .collect()
+}
+
+fn find_antinodes(
     antenna_positions: &AntennaPositions,
-    map: &Vec<Vec<char>>,
-) -> usize {
-    let mut result = HashSet::<Pos>::new();
-    // find
-    for (_, frequency_positions) in antenna_positions {
-        frequency_positions
-            .iter()
-            .combinations(2)
-            .for_each(|positions| {
-                result.insert(*positions[0]);
-                result.insert(*positions[1]);
-                (1..)
-                    .map_while(|i| {
-                        println!("{}", i);
-                        let pos = positions[0].plus_difference(*positions[1], i);
-                        is_valid(pos, map).then_some(pos)
-                    })
-                    .for_each(|pos| {
-                        result.insert(pos);
-                    });
-                (-100..-1)
-                    .rev()
-                    .map_while(|i| {
-                        println!("{}", i);
-                        let pos = positions[0].plus_difference(*positions[1], i);
-                        is_valid(pos, map).then_some(pos)
-                    })
-                    .for_each(|pos| {
-                        result.insert(pos);
-                    });
-            })
-    }
-
-    result.len()
+    map: &[Vec<char>],
+    mode: AntinodeMode,
+) -> HashSet<Pos> {
+    antenna_positions
+        .values()
+        .flat_map(|frequency_positions| antinodes_of_frequency(frequency_positions, map, mode))
+        .collect()
+}
+
+/// Draws the antenna map with `#` on every free cell that holds an antinode.
+fn render_antinode_map(map: &[Vec<char>], antinodes: &HashSet<Pos>) -> String {
+    map.iter()
+        .enumerate()
+        .map(|(row, line)| {
+            line.iter()
+                .enumerate()
+                .map(|(col, &c)| {
+                    let is_antinode = antinodes.contains(&Pos::new(row as i32, col as i32));
+                    if c == '.' && is_antinode {
+                        '#'
+                    } else {
+                        c
+                    }
+                })
+                .collect::<String>()
+        })
+        .join("\n")
+}
+
+#[derive(Debug)]
+struct FrequencyStats {
+    frequency: char,
+    antennas: usize,
+    pairs: usize,
+    antinodes: usize,
+}
+
+fn frequency_stats(
+    antenna_positions: &AntennaPositions,
+    map: &[Vec<char>],
+    mode: AntinodeMode,
+) -> Vec<FrequencyStats> {
+    antenna_positions
+        .iter()
+        .sorted_by_key(|(frequency, _)| **frequency)
+        .map(|(&frequency, positions)| FrequencyStats {
+            frequency,
+            antennas: positions.len(),
+            pairs: positions.len() * positions.len().saturating_sub(1) / 2,
+            antinodes: antinodes_of_frequency(positions, map, mode).len(),
+        })
+        .collect_vec()
+}
+
+fn print_stats_table(stats: &[FrequencyStats]) {
+    println!(
+        "{:>9} {:>8} {:>6} {:>9}",
+        "frequency", "antennas", "pairs", "antinodes"
+    );
+    for s in stats {
+        println!(
+            "{:>9} {:>8} {:>6} {:>9}",
+            s.frequency, s.antennas, s.pairs, s.antinodes
+        );
+    }
+}
+
+/// Quotes `s` as a JSON string, escaping quotes, backslashes and control characters.
+fn json_string(s: &str) -> String {
+    let escaped: String = s
+        .chars()
+        .map(|c| match c {
+            '"' => "\\\"".to_string(),
+            '\\' => "\\\\".to_string(),
+            c if c.is_control() => format!("\\u{:04x}", c as u32),
+            c => c.to_string(),
+        })
+        .collect();
+    format!("\"{}\"", escaped)
+}
+
+fn stats_to_json(stats: &[FrequencyStats]) -> String {
+    let entries = stats
+        .iter()
+        .map(|s| {
+            format!(
+                "{{\"frequency\":{},\"antennas\":{},\"pairs\":{},\"antinodes\":{}}}",
+                json_string(&s.frequency.to_string()),
+                s.antennas,
+                s.pairs,
+                s.antinodes
+            )
+        })
+        .join(",");
+    format!("[{}]", entries)
+}
+
+fn parse_mode(args: &[String]) -> AntinodeMode {
+    let collinear = args.iter().any(|a| a == "--collinear");
+    match args.iter().position(|a| a == "--part") {
+        None if collinear => AntinodeMode::Collinear,
+        None => AntinodeMode::Harmonics,
+        Some(_) if collinear => panic!("--collinear cannot be combined with --part"),
+        Some(i) => match args.get(i + 1).map(String::as_str) {
+            Some("1") => AntinodeMode::Pair,
+            Some("2") => AntinodeMode::Harmonics,
+            other => panic!("Usage: --part <1|2>, got {:?}", other),
+        },
+    }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let file_path = &args[1];
+    let mode = parse_mode(&args[2..]);
 
     let lines = parse_file_to_lines(file_path);
     let antenna_positions = find_antennas(&lines);
 
-    println!(
-        "{}",
-        find_count_pos_with_antinode(&antenna_positions, &lines)
-    );
+    let antinodes = find_antinodes(&antenna_positions, &lines, mode);
+
+    if args.iter().any(|a| a == "--render") {
+        println!("{}", render_antinode_map(&lines, &antinodes));
+    }
+    if args.iter().any(|a| a == "--stats") {
+        let stats = frequency_stats(&antenna_positions, &lines, mode);
+        if args.iter().any(|a| a == "--json") {
+            println!("{}", stats_to_json(&stats));
+        } else {
+            print_stats_table(&stats);
+        }
+    }
+
+    println!("{}", antinodes.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+............
+........0...
+.....0......
+.......0....
+....0.......
+......A.....
+............
+............
+........A...
+.........A..
+............
+............";
+
+    fn example() -> Vec<Vec<char>> {
+        EXAMPLE.lines().map(|line| line.chars().collect()).collect()
+    }
+
+    fn count_antinodes(map: &[Vec<char>], mode: AntinodeMode) -> usize {
+        find_antinodes(&find_antennas(map), map, mode).len()
+    }
+
+    #[test]
+    fn example_counts() {
+        let map = example();
+        assert_eq!(count_antinodes(&map, AntinodeMode::Pair), 14);
+        assert_eq!(count_antinodes(&map, AntinodeMode::Harmonics), 34);
+        assert_eq!(count_antinodes(&map, AntinodeMode::Collinear), 34);
+    }
+
+    #[test]
+    fn harmonics_reach_across_wide_maps() {
+        let mut row = vec!['.'; 301];
+        row[0] = 'a';
+        row[1] = 'a';
+        assert_eq!(count_antinodes(&[row], AntinodeMode::Harmonics), 301);
+    }
+
+    #[test]
+    fn example_render() {
+        let map = example();
+        let antinodes = find_antinodes(&find_antennas(&map), &map, AntinodeMode::Pair);
+        let expected = "\
+......#....#
+...#....0...
+....#0....#.
+..#....0....
+....0....#..
+.#....A.....
+...#........
+#......#....
+........A...
+.........A..
+..........#.
+..........#.";
+        assert_eq!(render_antinode_map(&map, &antinodes), expected);
+    }
+
+    #[test]
+    fn example_stats() {
+        let map = example();
+        let stats = frequency_stats(&find_antennas(&map), &map, AntinodeMode::Pair);
+        let rows = stats
+            .iter()
+            .map(|s| (s.frequency, s.antennas, s.pairs, s.antinodes))
+            .collect_vec();
+        assert_eq!(rows, vec![('0', 4, 6, 10), ('A', 3, 3, 5)]);
+        assert_eq!(
+            stats_to_json(&stats),
+            "[{\"frequency\":\"0\",\"antennas\":4,\"pairs\":6,\"antinodes\":10},\
+             {\"frequency\":\"A\",\"antennas\":3,\"pairs\":3,\"antinodes\":5}]"
+        );
+    }
+
+    #[test]
+    fn json_escaping() {
+        assert_eq!(json_string("a"), "\"a\"");
+        assert_eq!(json_string("\""), "\"\\\"\"");
+        assert_eq!(json_string("\\"), "\"\\\\\"");
+        assert_eq!(json_string("\u{1b}"), "\"\\u001b\"");
+    }
+
+    fn args(v: &[&str]) -> Vec<String> {
+        v.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_modes() {
+        assert_eq!(parse_mode(&args(&["--part", "1"])), AntinodeMode::Pair);
+        assert_eq!(parse_mode(&args(&[])), AntinodeMode::Harmonics);
+        assert_eq!(parse_mode(&args(&["--collinear"])), AntinodeMode::Collinear);
+    }
+
+    #[test]
+    #[should_panic(expected = "--collinear cannot be combined with --part")]
+    fn collinear_rejects_part() {
+        parse_mode(&args(&["--collinear", "--part", "1"]));
+    }
+
+    #[test]
+    fn collinear_includes_points_between_the_antennas() {
+        let map = vec![vec!['.'; 5]; 3];
+        let (a, b) = (Pos::new(0, 0), Pos::new(2, 4));
+
+        let harmonics = antinodes_of_pair(a, b, AntinodeMode::Harmonics, &map);
+        let collinear = antinodes_of_pair(a, b, AntinodeMode::Collinear, &map);
+
+        assert!(!harmonics.contains(&Pos::new(1, 2)));
+        assert!(collinear.contains(&Pos::new(1, 2)));
+        assert_eq!(harmonics.len(), 2);
+        assert_eq!(collinear.len(), 3);
+    }
 }